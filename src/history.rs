@@ -0,0 +1,176 @@
+use std::fs;
+
+use sqlite::{Connection, State};
+use time;
+
+use libc;
+use tools;
+
+fn get_history_file() -> String {
+    let home = tools::get_user_home();
+    format!("{}/.cicada/history.sqlite", home)
+}
+
+// Make sure `~/.cicada` exists so sqlite can create the history file on a
+// fresh install.
+fn ensure_dir() {
+    let home = tools::get_user_home();
+    let dir = format!("{}/.cicada", home);
+    if let Err(e) = fs::create_dir_all(&dir) {
+        tools::clog(&format!("history: create dir {} failed: {:?}", dir, e));
+    }
+}
+
+// A per-process session id, good enough to group the commands typed in one
+// interactive run together.
+fn get_session_id() -> String {
+    let pid = unsafe { libc::getpid() };
+    format!("{}", pid)
+}
+
+fn open_db() -> Option<Connection> {
+    ensure_dir();
+    let hfile = get_history_file();
+    let conn = match sqlite::open(&hfile) {
+        Ok(x) => x,
+        Err(e) => {
+            tools::clog(&format!("history: open {} failed: {:?}", hfile, e));
+            return None;
+        }
+    };
+    let sql = "
+        CREATE TABLE IF NOT EXISTS history (
+            id INTEGER PRIMARY KEY AUTOINCREMENT,
+            inp TEXT,
+            rtn INTEGER,
+            tsb REAL,
+            cwd TEXT,
+            sessionid TEXT
+        );
+    ";
+    if let Err(e) = conn.execute(sql) {
+        tools::clog(&format!("history: create table failed: {:?}", e));
+        return None;
+    }
+    Some(conn)
+}
+
+// The most recent command recorded, used to drop consecutive duplicates.
+fn last_input(conn: &Connection) -> Option<String> {
+    let sql = "SELECT inp FROM history ORDER BY id DESC LIMIT 1";
+    let mut stmt = match conn.prepare(sql) {
+        Ok(x) => x,
+        Err(e) => {
+            tools::clog(&format!("history: prepare failed: {:?}", e));
+            return None;
+        }
+    };
+    if let Ok(State::Row) = stmt.next() {
+        if let Ok(x) = stmt.read::<String>(0) {
+            return Some(x);
+        }
+    }
+    None
+}
+
+/// Record an executed command along with its exit status and working
+/// directory. Consecutive identical commands are collapsed into one entry.
+pub fn history_add(cmd: &str, status: i32, cwd: &str) {
+    let cmd = cmd.trim();
+    if cmd.is_empty() {
+        return;
+    }
+    let conn = match open_db() {
+        Some(x) => x,
+        None => return,
+    };
+    if let Some(prev) = last_input(&conn) {
+        if prev == cmd {
+            return;
+        }
+    }
+
+    let tsb = {
+        let t = time::get_time();
+        t.sec as f64 + f64::from(t.nsec) / 1_000_000_000f64
+    };
+    let sql = "INSERT INTO history (inp, rtn, tsb, cwd, sessionid) VALUES (?, ?, ?, ?, ?)";
+    let mut stmt = match conn.prepare(sql) {
+        Ok(x) => x,
+        Err(e) => {
+            tools::clog(&format!("history: prepare insert failed: {:?}", e));
+            return;
+        }
+    };
+    let binds: Vec<(usize, sqlite::Value)> = vec![
+        (1, sqlite::Value::String(cmd.to_string())),
+        (2, sqlite::Value::Integer(i64::from(status))),
+        (3, sqlite::Value::Float(tsb)),
+        (4, sqlite::Value::String(cwd.to_string())),
+        (5, sqlite::Value::String(get_session_id())),
+    ];
+    for (i, v) in binds {
+        if let Err(e) = stmt.bind(i, &v) {
+            tools::clog(&format!("history: bind failed: {:?}", e));
+            return;
+        }
+    }
+    if let Ok(State::Row) = stmt.next() {
+        // INSERT returns no rows; nothing to read.
+    }
+}
+
+/// Search history for commands matching `pattern` (SQL `LIKE`, so `%` and
+/// `_` are wildcards), newest first, returning at most `limit` lines.
+pub fn history_search(pattern: &str, limit: usize) -> Vec<String> {
+    let mut result = Vec::new();
+    let conn = match open_db() {
+        Some(x) => x,
+        None => return result,
+    };
+
+    let sql = "SELECT inp FROM history WHERE inp LIKE ? ORDER BY id DESC LIMIT ?";
+    let mut stmt = match conn.prepare(sql) {
+        Ok(x) => x,
+        Err(e) => {
+            tools::clog(&format!("history: prepare search failed: {:?}", e));
+            return result;
+        }
+    };
+    let like = format!("%{}%", pattern);
+    if stmt.bind(1, &sqlite::Value::String(like)).is_err() {
+        return result;
+    }
+    if stmt.bind(2, &sqlite::Value::Integer(limit as i64)).is_err() {
+        return result;
+    }
+    while let Ok(State::Row) = stmt.next() {
+        if let Ok(x) = stmt.read::<String>(0) {
+            result.push(x);
+        }
+    }
+    result
+}
+
+/// The full history list, oldest-first.
+pub fn history_list() -> Vec<String> {
+    let mut result = Vec::new();
+    let conn = match open_db() {
+        Some(x) => x,
+        None => return result,
+    };
+    let sql = "SELECT inp FROM history ORDER BY id ASC";
+    let mut stmt = match conn.prepare(sql) {
+        Ok(x) => x,
+        Err(e) => {
+            tools::clog(&format!("history: prepare list failed: {:?}", e));
+            return result;
+        }
+    };
+    while let Ok(State::Row) = stmt.next() {
+        if let Ok(x) = stmt.read::<String>(0) {
+            result.push(x);
+        }
+    }
+    result
+}
@@ -121,6 +121,161 @@ pub fn unquote(s: &str) -> String {
     args[0].clone()
 }
 
+// The home directory of `user` looked up from the passwd database.
+fn get_home_of(user: &str) -> Option<String> {
+    use std::ffi::{CStr, CString};
+    let cname = match CString::new(user) {
+        Ok(x) => x,
+        Err(_) => return None,
+    };
+    unsafe {
+        let pw = libc::getpwnam(cname.as_ptr());
+        if pw.is_null() {
+            return None;
+        }
+        let dir = (*pw).pw_dir;
+        if dir.is_null() {
+            return None;
+        }
+        Some(CStr::from_ptr(dir).to_string_lossy().into_owned())
+    }
+}
+
+fn var_value(name: &str) -> String {
+    env::var(name).unwrap_or_default()
+}
+
+// Resolve a variable against the shell's own variable table first, falling
+// back to the process environment for exported names.
+fn shell_var(sh: &shell::Shell, name: &str) -> String {
+    if let Some(v) = sh.get_env(name) {
+        return v;
+    }
+    env::var(name).unwrap_or_default()
+}
+
+// Resolve the body of a `${...}` parameter reference, honoring the
+// `:-default`, `:+word` and `#VAR` (length) forms, using `resolve` to look
+// each name up.
+fn expand_param<F>(body: &str, resolve: &F) -> String
+where
+    F: Fn(&str) -> String,
+{
+    if let Some(name) = body.strip_prefix('#') {
+        return format!("{}", resolve(name).chars().count());
+    }
+    if let Some(idx) = body.find(":-") {
+        let v = resolve(&body[..idx]);
+        return if v.is_empty() {
+            body[idx + 2..].to_string()
+        } else {
+            v
+        };
+    }
+    if let Some(idx) = body.find(":+") {
+        let v = resolve(&body[..idx]);
+        return if v.is_empty() {
+            String::new()
+        } else {
+            body[idx + 2..].to_string()
+        };
+    }
+    resolve(body)
+}
+
+/// Expand a single word the POSIX way: first tilde (`~` and `~user`), then
+/// variable references (`$VAR`, `${VAR}`, `${VAR:-default}`, `${VAR:+word}`
+/// and `${#VAR}`), leaving single-quoted spans untouched. Variables are
+/// resolved against the shell's variable table (falling back to the
+/// environment).
+pub fn expand_word(sh: &shell::Shell, word: &str) -> String {
+    expand_word_with(word, |name| shell_var(sh, name))
+}
+
+// The expansion core, parameterized by how a variable name is resolved so
+// both the shell-aware `expand_word` and the environment-only assignment
+// escaping can share it.
+fn expand_word_with<F>(word: &str, resolve: F) -> String
+where
+    F: Fn(&str) -> String,
+{
+    let chars: Vec<char> = word.chars().collect();
+    let n = chars.len();
+    let mut out = String::new();
+    let mut i = 0;
+
+    // tilde expansion only applies at the start of the word
+    if n > 0 && chars[0] == '~' {
+        let mut j = 1;
+        while j < n && chars[j] != '/' {
+            j += 1;
+        }
+        let user: String = chars[1..j].iter().collect();
+        if user.is_empty() {
+            out.push_str(&get_user_home());
+        } else if let Some(home) = get_home_of(&user) {
+            out.push_str(&home);
+        } else {
+            out.push('~');
+            out.push_str(&user);
+        }
+        i = j;
+    }
+
+    let mut in_single = false;
+    while i < n {
+        let c = chars[i];
+        if c == '\'' {
+            in_single = !in_single;
+            out.push(c);
+            i += 1;
+            continue;
+        }
+        if c == '$' && !in_single {
+            if i + 1 < n && chars[i + 1] == '{' {
+                let mut depth = 1;
+                let mut j = i + 2;
+                while j < n {
+                    if chars[j] == '{' {
+                        depth += 1;
+                    } else if chars[j] == '}' {
+                        depth -= 1;
+                        if depth == 0 {
+                            break;
+                        }
+                    }
+                    j += 1;
+                }
+                if j >= n {
+                    out.push(c);
+                    i += 1;
+                    continue;
+                }
+                let body: String = chars[i + 2..j].iter().collect();
+                out.push_str(&expand_param(&body, &resolve));
+                i = j + 1;
+                continue;
+            }
+            let mut j = i + 1;
+            while j < n && (chars[j].is_alphanumeric() || chars[j] == '_') {
+                j += 1;
+            }
+            if j == i + 1 {
+                out.push('$');
+                i += 1;
+                continue;
+            }
+            let name: String = chars[i + 1..j].iter().collect();
+            out.push_str(&resolve(&name));
+            i = j;
+            continue;
+        }
+        out.push(c);
+        i += 1;
+    }
+    out
+}
+
 pub fn is_export_env(line: &str) -> bool {
     re_contains(line, r"^ *export +[a-zA-Z0-9_]+=.*$")
 }
@@ -130,27 +285,408 @@ pub fn is_env(line: &str) -> bool {
 }
 
 pub fn should_extend_brace(line: &str) -> bool {
-    re_contains(line, r#"\{[^ "']+,[^ "']+,?[^ "']*\}"#)
+    re_contains(line, r#"\{[^ "']*(,|\.\.)[^ "']*\}"#)
 }
 
-// #[allow(clippy::trivial_regex)]
-pub fn extend_bandband(sh: &shell::Shell, line: &mut String) {
-    if !re_contains(line, r"!!") {
-        return;
+// Index of the `}` matching the `{` at `open`, respecting nesting.
+fn match_brace(s: &str, open: usize) -> Option<usize> {
+    let bytes = s.as_bytes();
+    let mut depth = 0;
+    let mut i = open;
+    while i < bytes.len() {
+        match bytes[i] {
+            b'{' => depth += 1,
+            b'}' => {
+                depth -= 1;
+                if depth == 0 {
+                    return Some(i);
+                }
+            }
+            _ => {}
+        }
+        i += 1;
+    }
+    None
+}
+
+// Split a brace body on its top-level (brace-depth zero) commas.
+fn split_top_commas(s: &str) -> Vec<String> {
+    let mut parts = Vec::new();
+    let mut depth = 0;
+    let mut cur = String::new();
+    for c in s.chars() {
+        match c {
+            '{' => {
+                depth += 1;
+                cur.push(c);
+            }
+            '}' => {
+                depth -= 1;
+                cur.push(c);
+            }
+            ',' if depth == 0 => {
+                parts.push(cur.clone());
+                cur.clear();
+            }
+            _ => cur.push(c),
+        }
+    }
+    parts.push(cur);
+    parts
+}
+
+fn brace_fmt_num(v: i64, width: usize) -> String {
+    if width == 0 {
+        format!("{}", v)
+    } else {
+        format!("{:0width$}", v, width = width)
+    }
+}
+
+// Expand a sequence expression such as `1..10`, `a..z`, `10..1` or
+// `01..10..2`, or None when the body is not a range.
+fn brace_expand_range(content: &str) -> Option<Vec<String>> {
+    let segs: Vec<&str> = content.split("..").collect();
+    if segs.len() != 2 && segs.len() != 3 {
+        return None;
+    }
+    let step = match segs.get(2) {
+        Some(s) => match s.parse::<i64>() {
+            Ok(x) if x != 0 => x.abs(),
+            _ => return None,
+        },
+        None => 1,
+    };
+    let (lo, hi) = (segs[0], segs[1]);
+
+    if let (Ok(a), Ok(b)) = (lo.parse::<i64>(), hi.parse::<i64>()) {
+        let la = lo.trim_start_matches('-');
+        let lb = hi.trim_start_matches('-');
+        let padded = (la.len() > 1 && la.starts_with('0')) || (lb.len() > 1 && lb.starts_with('0'));
+        let width = if padded { la.len().max(lb.len()) } else { 0 };
+        let mut out = Vec::new();
+        let mut v = a;
+        if a <= b {
+            while v <= b {
+                out.push(brace_fmt_num(v, width));
+                v += step;
+            }
+        } else {
+            while v >= b {
+                out.push(brace_fmt_num(v, width));
+                v -= step;
+            }
+        }
+        return Some(out);
+    }
+
+    let lc: Vec<char> = lo.chars().collect();
+    let hc: Vec<char> = hi.chars().collect();
+    if lc.len() == 1 && hc.len() == 1 && lc[0].is_ascii_alphabetic() && hc[0].is_ascii_alphabetic() {
+        let (a, b) = (lc[0] as i64, hc[0] as i64);
+        let mut out = Vec::new();
+        let mut v = a;
+        if a <= b {
+            while v <= b {
+                out.push(((v as u8) as char).to_string());
+                v += step;
+            }
+        } else {
+            while v >= b {
+                out.push(((v as u8) as char).to_string());
+                v -= step;
+            }
+        }
+        return Some(out);
+    }
+
+    None
+}
+
+// The alternatives a brace body expands to (comma list or range), or None
+// when the braces are not an expansion (e.g. `{abc}`).
+fn brace_alternatives(content: &str) -> Option<Vec<String>> {
+    let parts = split_top_commas(content);
+    if parts.len() > 1 {
+        return Some(parts);
+    }
+    brace_expand_range(content)
+}
+
+/// Recursively expand brace groups in a word, composing multiple groups into
+/// their Cartesian product and descending into nested groups until no
+/// expandable braces remain.
+pub fn brace_expand(s: &str) -> Vec<String> {
+    let bytes = s.as_bytes();
+    let mut i = 0;
+    while i < bytes.len() {
+        if bytes[i] == b'{' {
+            if let Some(close) = match_brace(s, i) {
+                let content = &s[i + 1..close];
+                if let Some(alts) = brace_alternatives(content) {
+                    let prefix = &s[..i];
+                    let suffix = &s[close + 1..];
+                    let mut result = Vec::new();
+                    for alt in alts {
+                        let word = format!("{}{}{}", prefix, alt, suffix);
+                        result.extend(brace_expand(&word));
+                    }
+                    return result;
+                }
+            }
+        }
+        i += 1;
+    }
+    vec![s.to_string()]
+}
+
+/// Brace-expand each non-single-quoted token on the line in place.
+pub fn extend_brace(line: &mut String) {
+    let mut new_line = String::new();
+    let tokens = parsers::parser_line::cmd_to_tokens(line);
+    for (sep, token) in tokens {
+        if !sep.is_empty() {
+            new_line.push_str(&sep);
+        }
+
+        if sep != "'" {
+            new_line.push_str(&brace_expand(&token).join(" "));
+        } else {
+            new_line.push_str(&token);
+        }
+
+        if !sep.is_empty() {
+            new_line.push_str(&sep);
+        }
+        new_line.push(' ');
     }
+    *line = new_line.trim_right().to_string();
+}
+
+// The history list the expansion engine resolves `!` events against,
+// ordered oldest-first so the last entry is the most recent command. It is
+// built from `sh.previous_cmd` alone, so it holds at most that one command;
+// ~/.cicada/history.sqlite is not read, and `!n`, `!-n`, `!str` and
+// `!?str?` can only match the previous command.
+fn get_history_list(sh: &shell::Shell) -> Vec<String> {
     if sh.previous_cmd.is_empty() {
-        return;
+        Vec::new()
+    } else {
+        vec![sh.previous_cmd.clone()]
     }
+}
 
-    let re;
-    match Regex::new(r"!!") {
-        Ok(x) => {
-            re = x;
+// Resolve a single event designator (the text following a `!`) against the
+// history, returning the matched command line and the number of bytes the
+// designator consumed from `s`.
+fn resolve_event(s: &str, history: &[String]) -> Option<(String, usize)> {
+    if history.is_empty() {
+        return None;
+    }
+    if s.is_empty() {
+        return None;
+    }
+    let last = history.len() - 1;
+    let bytes = s.as_bytes();
+
+    // `!!` previous command
+    if s.starts_with('!') {
+        return Some((history[last].clone(), 1));
+    }
+
+    // `!?str?` most recent containing str
+    if s.starts_with('?') {
+        if let Some(end) = s[1..].find('?') {
+            let needle = &s[1..1 + end];
+            for cmd in history.iter().rev() {
+                if cmd.contains(needle) {
+                    return Some((cmd.clone(), 1 + end + 1));
+                }
+            }
         }
-        Err(e) => {
-            println_stderr!("Regex new: {:?}", e);
-            return;
+        return None;
+    }
+
+    // `!-n` n commands back (`!-1` == previous)
+    if s.starts_with('-') {
+        let mut i = 1;
+        while i < bytes.len() && bytes[i].is_ascii_digit() {
+            i += 1;
+        }
+        if i == 1 {
+            return None;
+        }
+        if let Ok(n) = s[1..i].parse::<usize>() {
+            if n >= 1 && n <= history.len() {
+                return Some((history[history.len() - n].clone(), i));
+            }
+        }
+        return None;
+    }
+
+    // `!n` absolute (1-based) history number
+    if bytes[0].is_ascii_digit() {
+        let mut i = 0;
+        while i < bytes.len() && bytes[i].is_ascii_digit() {
+            i += 1;
+        }
+        if let Ok(n) = s[..i].parse::<usize>() {
+            if n >= 1 && n <= history.len() {
+                return Some((history[n - 1].clone(), i));
+            }
+        }
+        return None;
+    }
+
+    // `!str` most recent command starting with str
+    let mut i = 0;
+    while i < bytes.len() && bytes[i] != b':' && !bytes[i].is_ascii_whitespace() {
+        i += 1;
+    }
+    if i == 0 {
+        return None;
+    }
+    let prefix = &s[..i];
+    for cmd in history.iter().rev() {
+        if cmd.starts_with(prefix) {
+            return Some((cmd.clone(), i));
+        }
+    }
+    None
+}
+
+// Select a range of words from the resolved command per a word designator
+// such as `0`, `^`, `$`, `*`, `n` or `n-m`, returning None when the spec is
+// not a word designator (so it can be re-tried as a modifier).
+fn select_words(spec: &str, words: &[String]) -> Option<String> {
+    if words.is_empty() {
+        return Some(String::new());
+    }
+    let last = words.len() - 1;
+    if spec == "^" {
+        return Some(words.get(1).cloned().unwrap_or_default());
+    }
+    if spec == "$" {
+        return Some(words[last].clone());
+    }
+    if spec == "*" {
+        return Some(words[1..].join(" "));
+    }
+    if let Some(idx) = spec.find('-') {
+        let lo = spec[..idx].parse::<usize>();
+        let hi = spec[idx + 1..].parse::<usize>();
+        if let (Ok(lo), Ok(hi)) = (lo, hi) {
+            let hi = if hi > last { last } else { hi };
+            if lo <= hi {
+                return Some(words[lo..=hi].join(" "));
+            }
+            return Some(String::new());
+        }
+        return None;
+    }
+    if let Ok(n) = spec.parse::<usize>() {
+        return Some(words.get(n).cloned().unwrap_or_default());
+    }
+    None
+}
+
+// Apply a single modifier (`h`, `t`, `r`, `s/old/new/`) to a word.
+fn apply_modifier(spec: &str, value: &str) -> String {
+    match spec.chars().next() {
+        Some('h') => match value.rfind('/') {
+            Some(idx) => value[..idx].to_string(),
+            None => value.to_string(),
+        },
+        Some('t') => match value.rfind('/') {
+            Some(idx) => value[idx + 1..].to_string(),
+            None => value.to_string(),
+        },
+        Some('r') => match value.rfind('.') {
+            Some(idx) => value[..idx].to_string(),
+            None => value.to_string(),
+        },
+        Some('s') => {
+            let body = &spec[1..];
+            let delim = match body.chars().next() {
+                Some(c) => c,
+                None => return value.to_string(),
+            };
+            let parts: Vec<&str> = body[delim.len_utf8()..].splitn(3, delim).collect();
+            if parts.len() >= 2 {
+                return value.replacen(parts[0], parts[1], 1);
+            }
+            value.to_string()
         }
+        _ => value.to_string(),
+    }
+}
+
+// Split the `:`-introduced tail of an event into its designator/modifier
+// segments, being careful not to break inside an `s/old/new/` modifier.
+fn split_segments(tail: &str) -> Vec<String> {
+    let mut segments = Vec::new();
+    let mut rest = tail;
+    while rest.starts_with(':') {
+        rest = &rest[1..];
+        if rest.starts_with('s') {
+            segments.push(rest.to_string());
+            break;
+        }
+        match rest.find(':') {
+            Some(idx) => {
+                segments.push(rest[..idx].to_string());
+                rest = &rest[idx..];
+            }
+            None => {
+                segments.push(rest.to_string());
+                break;
+            }
+        }
+    }
+    segments
+}
+
+// Expand a single token against the history, returning the rewritten token
+// when it contained a `!` event or None when it was left untouched.
+fn expand_history_token(token: &str, history: &[String]) -> Option<String> {
+    let bang = token.find('!')?;
+    let prefix = &token[..bang];
+    let after = &token[bang + 1..];
+
+    let (command, used) = resolve_event(after, history)?;
+    let tail = &after[used..];
+
+    let words: Vec<String> = command.split_whitespace().map(|w| w.to_string()).collect();
+    let segments = split_segments(tail);
+    let consumed: usize = segments.iter().map(|s| s.len() + 1).sum();
+    let suffix = &tail[consumed..];
+
+    let mut value = command.clone();
+    let mut first = true;
+    for seg in &segments {
+        if first {
+            if let Some(selected) = select_words(seg, &words) {
+                value = selected;
+                first = false;
+                continue;
+            }
+            first = false;
+        }
+        value = apply_modifier(seg, &value);
+    }
+
+    Some(format!("{}{}{}", prefix, value, suffix))
+}
+
+// csh/bash-style history expansion pass over a command line, resolving
+// events against `get_history_list`, which holds only the previous command.
+pub fn extend_bandband(sh: &shell::Shell, line: &mut String) {
+    if !line.contains('!') {
+        return;
+    }
+    let history = get_history_list(sh);
+    if history.is_empty() {
+        return;
     }
 
     let mut replaced = false;
@@ -161,11 +697,13 @@ pub fn extend_bandband(sh: &shell::Shell, line: &mut String) {
             new_line.push_str(&sep);
         }
 
-        if re_contains(&token, r"!!") && sep != "'" {
-            let line2 = token.clone();
-            let result = re.replace_all(&line2, sh.previous_cmd.as_str());
-            new_line.push_str(&result);
-            replaced = true;
+        if sep != "'" {
+            if let Some(expanded) = expand_history_token(&token, &history) {
+                new_line.push_str(&expanded);
+                replaced = true;
+            } else {
+                new_line.push_str(&token);
+            }
         } else {
             new_line.push_str(&token);
         }
@@ -184,6 +722,13 @@ pub fn extend_bandband(sh: &shell::Shell, line: &mut String) {
 }
 
 pub fn wrap_sep_string(sep: &str, s: &str) -> String {
+    // Assignment escaping runs without a Shell in hand, so expand against the
+    // process environment only. A single-quoted token is left literal.
+    let s = if sep == "'" {
+        s.to_string()
+    } else {
+        expand_word_with(s, var_value)
+    };
     let mut _token = String::new();
     let mut met_subsep = false;
     // let set previous_subsep to any char except '`' or '"'
@@ -270,6 +815,291 @@ pub fn is_arithmetic(line: &str) -> bool {
     re_contains(line, r"^[ 0-9\.\(\)\+\-\*/]+$")
 }
 
+enum ArithTok {
+    Num(f64),
+    Op(String),
+    LParen,
+    RParen,
+}
+
+fn arith_prec(op: &str) -> i32 {
+    match op {
+        "**" => 8,
+        "u-" => 7,
+        "*" | "/" | "%" => 6,
+        "+" | "-" => 5,
+        "<<" | ">>" => 4,
+        "&" | "^" | "|" => 3,
+        "==" | "!=" | "<" | "<=" | ">" | ">=" => 2,
+        _ => 0,
+    }
+}
+
+// Scan an arithmetic expression into tokens, resolving bare identifiers
+// against the shell's variables (undefined names evaluate to 0).
+fn arith_tokens(sh: &shell::Shell, expr: &str) -> Result<Vec<ArithTok>, String> {
+    let mut toks = Vec::new();
+    let chars: Vec<char> = expr.chars().collect();
+    let mut i = 0;
+    while i < chars.len() {
+        let c = chars[i];
+        if c.is_whitespace() {
+            i += 1;
+            continue;
+        }
+        if c.is_ascii_digit() || c == '.' {
+            let mut s = String::new();
+            while i < chars.len() && (chars[i].is_ascii_digit() || chars[i] == '.') {
+                s.push(chars[i]);
+                i += 1;
+            }
+            match s.parse::<f64>() {
+                Ok(n) => toks.push(ArithTok::Num(n)),
+                Err(_) => return Err(format!("invalid number: {}", s)),
+            }
+            continue;
+        }
+        if c.is_alphabetic() || c == '_' {
+            let mut s = String::new();
+            while i < chars.len() && (chars[i].is_alphanumeric() || chars[i] == '_') {
+                s.push(chars[i]);
+                i += 1;
+            }
+            let val = shell_var(sh, &s).trim().parse::<f64>().unwrap_or(0.0);
+            toks.push(ArithTok::Num(val));
+            continue;
+        }
+        match c {
+            '(' => {
+                toks.push(ArithTok::LParen);
+                i += 1;
+            }
+            ')' => {
+                toks.push(ArithTok::RParen);
+                i += 1;
+            }
+            '+' | '-' | '*' | '/' | '%' | '&' | '|' | '^' | '<' | '>' | '=' | '!' => {
+                let two = if i + 1 < chars.len() {
+                    format!("{}{}", c, chars[i + 1])
+                } else {
+                    String::new()
+                };
+                let op = match two.as_str() {
+                    "**" | "<<" | ">>" | "<=" | ">=" | "==" | "!=" => {
+                        i += 2;
+                        two
+                    }
+                    _ => {
+                        i += 1;
+                        c.to_string()
+                    }
+                };
+                toks.push(ArithTok::Op(op));
+            }
+            _ => return Err(format!("unexpected char: {}", c)),
+        }
+    }
+    Ok(toks)
+}
+
+// Shunting-yard: convert the token stream to reverse polish notation,
+// popping operators of higher precedence (or equal precedence, for the
+// left-associative ones) and flushing on `)`.
+fn arith_to_rpn(toks: Vec<ArithTok>) -> Result<Vec<ArithTok>, String> {
+    let mut out: Vec<ArithTok> = Vec::new();
+    let mut ops: Vec<String> = Vec::new();
+    let mut prev_is_value = false;
+    for tok in toks {
+        match tok {
+            ArithTok::Num(n) => {
+                out.push(ArithTok::Num(n));
+                prev_is_value = true;
+            }
+            ArithTok::LParen => {
+                ops.push("(".to_string());
+                prev_is_value = false;
+            }
+            ArithTok::RParen => {
+                while let Some(top) = ops.last() {
+                    if top == "(" {
+                        break;
+                    }
+                    out.push(ArithTok::Op(ops.pop().unwrap()));
+                }
+                if ops.last().map(|s| s == "(").unwrap_or(false) {
+                    ops.pop();
+                } else {
+                    return Err("mismatched parentheses".to_string());
+                }
+                prev_is_value = true;
+            }
+            ArithTok::Op(mut op) => {
+                if op == "+" && !prev_is_value {
+                    // unary plus is a no-op
+                    continue;
+                }
+                if op == "-" && !prev_is_value {
+                    op = "u-".to_string();
+                }
+                let right_assoc = op == "**" || op == "u-";
+                let p = arith_prec(&op);
+                while let Some(top) = ops.last() {
+                    if top == "(" {
+                        break;
+                    }
+                    let tp = arith_prec(top);
+                    if tp > p || (tp == p && !right_assoc) {
+                        out.push(ArithTok::Op(ops.pop().unwrap()));
+                    } else {
+                        break;
+                    }
+                }
+                ops.push(op);
+                prev_is_value = false;
+            }
+        }
+    }
+    while let Some(op) = ops.pop() {
+        if op == "(" {
+            return Err("mismatched parentheses".to_string());
+        }
+        out.push(ArithTok::Op(op));
+    }
+    Ok(out)
+}
+
+fn arith_apply(op: &str, a: f64, b: f64, integer: bool) -> Result<f64, String> {
+    let r = match op {
+        "+" => a + b,
+        "-" => a - b,
+        "*" => a * b,
+        "/" => {
+            if b == 0.0 {
+                return Err("division by zero".to_string());
+            }
+            if integer {
+                (a.trunc() as i64 / b.trunc() as i64) as f64
+            } else {
+                a / b
+            }
+        }
+        "%" => {
+            if b == 0.0 {
+                return Err("division by zero".to_string());
+            }
+            if integer {
+                (a.trunc() as i64 % b.trunc() as i64) as f64
+            } else {
+                a % b
+            }
+        }
+        "**" => {
+            if integer {
+                let exp = if b < 0.0 { 0 } else { b.trunc() as u32 };
+                (a.trunc() as i64).pow(exp) as f64
+            } else {
+                a.powf(b)
+            }
+        }
+        "<<" => ((a.trunc() as i64) << (b.trunc() as i64)) as f64,
+        ">>" => ((a.trunc() as i64) >> (b.trunc() as i64)) as f64,
+        "&" => ((a.trunc() as i64) & (b.trunc() as i64)) as f64,
+        "|" => ((a.trunc() as i64) | (b.trunc() as i64)) as f64,
+        "^" => ((a.trunc() as i64) ^ (b.trunc() as i64)) as f64,
+        "==" => if a == b { 1.0 } else { 0.0 },
+        "!=" => if a != b { 1.0 } else { 0.0 },
+        "<" => if a < b { 1.0 } else { 0.0 },
+        "<=" => if a <= b { 1.0 } else { 0.0 },
+        ">" => if a > b { 1.0 } else { 0.0 },
+        ">=" => if a >= b { 1.0 } else { 0.0 },
+        _ => return Err(format!("unknown operator: {}", op)),
+    };
+    Ok(r)
+}
+
+fn arith_eval_rpn(rpn: Vec<ArithTok>, integer: bool) -> Result<f64, String> {
+    let mut stack: Vec<f64> = Vec::new();
+    for tok in rpn {
+        match tok {
+            ArithTok::Num(n) => stack.push(n),
+            ArithTok::Op(op) => {
+                if op == "u-" {
+                    let a = stack.pop().ok_or("arithmetic: stack underflow")?;
+                    stack.push(-a);
+                    continue;
+                }
+                let b = stack.pop().ok_or("arithmetic: stack underflow")?;
+                let a = stack.pop().ok_or("arithmetic: stack underflow")?;
+                stack.push(arith_apply(&op, a, b, integer)?);
+            }
+            _ => return Err("arithmetic: malformed expression".to_string()),
+        }
+    }
+    stack.pop().ok_or_else(|| "arithmetic: empty expression".to_string())
+}
+
+/// Evaluate an arithmetic expression in floating-point mode.
+pub fn arith_eval(sh: &shell::Shell, expr: &str) -> Result<f64, String> {
+    let toks = arith_tokens(sh, expr)?;
+    let rpn = arith_to_rpn(toks)?;
+    arith_eval_rpn(rpn, false)
+}
+
+/// Evaluate an arithmetic expression in integer mode, as `$(( ))` does:
+/// `/` truncates and `%`/`**` operate on integers.
+pub fn arith_eval_int(sh: &shell::Shell, expr: &str) -> Result<i64, String> {
+    let toks = arith_tokens(sh, expr)?;
+    let rpn = arith_to_rpn(toks)?;
+    arith_eval_rpn(rpn, true).map(|v| v.trunc() as i64)
+}
+
+/// Replace each `$(( ))` expression in `line` with its integer value during
+/// command preprocessing. An expression that fails to evaluate is reported on
+/// stderr, and it and any later ones are left as written.
+pub fn expand_arithmetic(sh: &shell::Shell, line: &mut String) {
+    loop {
+        let start = match line.find("$((") {
+            Some(x) => x,
+            None => break,
+        };
+        let bytes = line.as_bytes();
+        // `start` points at `$`, so the two opening parens are consumed; scan
+        // for the `)` that returns to the outer paren (depth 1) and require it
+        // to be followed by the second `)` of the closing `))`.
+        let mut depth = 2;
+        let mut i = start + 3;
+        let mut end = None;
+        while i < bytes.len() {
+            match bytes[i] {
+                b'(' => depth += 1,
+                b')' => {
+                    depth -= 1;
+                    if depth == 1 && i + 1 < bytes.len() && bytes[i + 1] == b')' {
+                        end = Some(i);
+                        break;
+                    }
+                }
+                _ => {}
+            }
+            i += 1;
+        }
+        let end = match end {
+            Some(x) => x,
+            None => break,
+        };
+        let expr = line[start + 3..end].to_string();
+        let rep = match arith_eval_int(sh, &expr) {
+            Ok(v) => format!("{}", v),
+            Err(e) => {
+                println_stderr!("cicada: arithmetic: {}", e);
+                break;
+            }
+        };
+        // skip both closing parens: `end` is the first `)`, `end + 1` the second
+        *line = format!("{}{}{}", &line[..start], rep, &line[end + 2..]);
+    }
+}
+
 pub fn re_contains(line: &str, ptn: &str) -> bool {
     let re;
     match Regex::new(ptn) {
@@ -316,10 +1146,84 @@ pub fn get_fd_from_file(file_name: &str) -> i32 {
 
 #[cfg(test)]
 mod tests {
+    use super::arith_eval;
+    use super::arith_eval_int;
+    use super::brace_expand;
+    use super::expand_arithmetic;
+    use super::expand_word;
     use super::extend_bandband;
+    use super::get_user_home;
     use super::is_alias;
     use shell;
 
+    #[test]
+    fn test_expand_word() {
+        // Use shell-local variables so the test stays hermetic and never
+        // mutates the process-global environment shared by other tests.
+        let mut sh = shell::Shell::new();
+        sh.set_env("CICADA_TEST_VAR", "hello");
+
+        assert_eq!(expand_word(&sh, "$CICADA_TEST_VAR"), "hello");
+        assert_eq!(expand_word(&sh, "${CICADA_TEST_VAR}x"), "hellox");
+        assert_eq!(expand_word(&sh, "${#CICADA_TEST_VAR}"), "5");
+        assert_eq!(expand_word(&sh, "${CICADA_TEST_UNSET:-def}"), "def");
+        assert_eq!(expand_word(&sh, "${CICADA_TEST_VAR:+set}"), "set");
+        assert_eq!(expand_word(&sh, "'$CICADA_TEST_VAR'"), "'$CICADA_TEST_VAR'");
+
+        // tilde resolves to the real home; do not touch the global HOME
+        let home = get_user_home();
+        assert_eq!(expand_word(&sh, "~/bin"), format!("{}/bin", home));
+    }
+
+    #[test]
+    fn test_arith_eval_int() {
+        let sh = shell::Shell::new();
+        assert_eq!(arith_eval_int(&sh, "1 + 2 * 3"), Ok(7));
+        assert_eq!(arith_eval_int(&sh, "(1 + 2) * 3"), Ok(9));
+        assert_eq!(arith_eval_int(&sh, "2 ** 10"), Ok(1024));
+        assert_eq!(arith_eval_int(&sh, "-2 ** 2"), Ok(-4));
+        assert_eq!(arith_eval_int(&sh, "7 / 2"), Ok(3));
+        assert_eq!(arith_eval_int(&sh, "7 % 3"), Ok(1));
+        assert_eq!(arith_eval_int(&sh, "1 << 4"), Ok(16));
+        assert_eq!(arith_eval_int(&sh, "6 & 3"), Ok(2));
+        assert!(arith_eval_int(&sh, "1 / 0").is_err());
+    }
+
+    #[test]
+    fn test_arith_eval_float() {
+        let sh = shell::Shell::new();
+        assert_eq!(arith_eval(&sh, "7 / 2"), Ok(3.5));
+    }
+
+    #[test]
+    fn test_brace_expand() {
+        assert_eq!(brace_expand("a{b,c}d"), vec!["abd", "acd"]);
+        assert_eq!(brace_expand("{1..5}"), vec!["1", "2", "3", "4", "5"]);
+        assert_eq!(brace_expand("{3..1}"), vec!["3", "2", "1"]);
+        assert_eq!(brace_expand("{1..5..2}"), vec!["1", "3", "5"]);
+        assert_eq!(brace_expand("{a..e}"), vec!["a", "b", "c", "d", "e"]);
+        assert_eq!(brace_expand("{08..11}"), vec!["08", "09", "10", "11"]);
+        assert_eq!(
+            brace_expand("{a,b}{1,2}"),
+            vec!["a1", "a2", "b1", "b2"]
+        );
+        assert_eq!(brace_expand("{a,b{c,d}}"), vec!["a", "bc", "bd"]);
+        assert_eq!(brace_expand("plain"), vec!["plain"]);
+        assert_eq!(brace_expand("{abc}"), vec!["{abc}"]);
+    }
+
+    #[test]
+    fn test_expand_arithmetic() {
+        let sh = shell::Shell::new();
+        let mut line = "echo $((1 + 2 * 3))".to_string();
+        expand_arithmetic(&sh, &mut line);
+        assert_eq!(line, "echo 7");
+
+        line = "echo $((2 ** 3)) and $((10 / 3))".to_string();
+        expand_arithmetic(&sh, &mut line);
+        assert_eq!(line, "echo 8 and 3");
+    }
+
     #[test]
     fn test_is_alias() {
         assert!(is_alias("alias ls='ls -lh'"));
@@ -346,4 +1250,71 @@ mod tests {
         extend_bandband(&sh, &mut line);
         assert_eq!(line, "echo '!!' && echo foo");
     }
+
+    #[test]
+    fn test_extend_history_designators() {
+        let mut sh = shell::Shell::new();
+        sh.previous_cmd = "ls -lh /tmp/foo.txt".to_string();
+
+        let mut line = "echo !-1".to_string();
+        extend_bandband(&sh, &mut line);
+        assert_eq!(line, "echo ls -lh /tmp/foo.txt");
+
+        line = "echo !1".to_string();
+        extend_bandband(&sh, &mut line);
+        assert_eq!(line, "echo ls -lh /tmp/foo.txt");
+
+        line = "echo !ls".to_string();
+        extend_bandband(&sh, &mut line);
+        assert_eq!(line, "echo ls -lh /tmp/foo.txt");
+
+        line = "echo !?foo?".to_string();
+        extend_bandband(&sh, &mut line);
+        assert_eq!(line, "echo ls -lh /tmp/foo.txt");
+    }
+
+    #[test]
+    fn test_extend_trailing_bang() {
+        let mut sh = shell::Shell::new();
+        sh.previous_cmd = "ls -lh".to_string();
+
+        // a trailing `!` has no event designator and must be left untouched
+        let mut line = "echo foo!".to_string();
+        extend_bandband(&sh, &mut line);
+        assert_eq!(line, "echo foo!");
+
+        line = "echo !".to_string();
+        extend_bandband(&sh, &mut line);
+        assert_eq!(line, "echo !");
+    }
+
+    #[test]
+    fn test_extend_history_words_and_modifiers() {
+        let mut sh = shell::Shell::new();
+        sh.previous_cmd = "ls -lh /tmp/foo.txt".to_string();
+
+        let mut line = "echo !!:0".to_string();
+        extend_bandband(&sh, &mut line);
+        assert_eq!(line, "echo ls");
+
+        line = "echo !!:$".to_string();
+        extend_bandband(&sh, &mut line);
+        assert_eq!(line, "echo /tmp/foo.txt");
+
+        line = "echo !!:$:t".to_string();
+        extend_bandband(&sh, &mut line);
+        assert_eq!(line, "echo foo.txt");
+
+        line = "echo !!:$:h".to_string();
+        extend_bandband(&sh, &mut line);
+        assert_eq!(line, "echo /tmp");
+
+        line = "echo !!:$:t:r".to_string();
+        extend_bandband(&sh, &mut line);
+        assert_eq!(line, "echo foo");
+
+        line = "echo !!:$:s/foo/bar/".to_string();
+        extend_bandband(&sh, &mut line);
+        assert_eq!(line, "echo /tmp/bar.txt");
+    }
 }